@@ -1,6 +1,6 @@
 use derive_more::From;
 use either::Either;
-use std::io;
+use std::{error, fmt, io};
 
 use super::framed::DispatcherError;
 
@@ -39,6 +39,49 @@ pub enum MqttError<E> {
     Io(io::Error),
 }
 
+impl<E: fmt::Display> fmt::Display for MqttError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MqttError::Service(e) => write!(f, "service error: {}", e),
+            MqttError::Protocol(e) => write!(f, "protocol error: {}", e),
+            MqttError::PublishReadyError => f.write_str("publish service is not ready"),
+            MqttError::Decode(e) => write!(f, "decode error: {}", e),
+            MqttError::Encode(e) => write!(f, "encode error: {}", e),
+            MqttError::Unexpected(tp, name) => {
+                write!(f, "unexpected packet {:#x}, expected {}", tp, name)
+            }
+            MqttError::PacketIdRequired => {
+                f.write_str("packet with QoS > 0 must contain a non-zero packet id")
+            }
+            MqttError::DuplicatedPacketId => {
+                f.write_str("multiple in-flight publish packets share a packet id")
+            }
+            MqttError::PacketIdMismatch => {
+                f.write_str("ack packet id does not match the sent publish packet")
+            }
+            MqttError::MaxTopicAlias => f.write_str("topic alias is greater than max topic alias"),
+            MqttError::UnknownTopicAlias => f.write_str("unknown topic alias"),
+            MqttError::KeepAliveTimeout => f.write_str("keep alive timeout"),
+            MqttError::HandshakeTimeout => f.write_str("handshake timeout"),
+            MqttError::Disconnected => f.write_str("peer disconnected"),
+            MqttError::Io(e) => write!(f, "io error: {}", e),
+        }
+    }
+}
+
+impl<E: error::Error + 'static> error::Error for MqttError<E> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            MqttError::Service(e) => Some(e),
+            MqttError::Protocol(e) => Some(e),
+            MqttError::Decode(e) => Some(e),
+            MqttError::Encode(e) => Some(e),
+            MqttError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
 /// Protocol level errors
 #[derive(Debug)]
 pub enum ProtocolError {
@@ -56,6 +99,12 @@ pub enum ProtocolError {
     DuplicatedPacketId,
     /// Packet id of publish ack packet does not match of send publish packet
     PacketIdMismatch,
+    /// Received a PUBREL for a packet id that is not awaiting release
+    UnexpectedPubRel,
+    /// Received a PUBCOMP for a packet id that is not awaiting completion
+    UnexpectedPubComp,
+    /// QoS 2 control packet carried a failure reason code
+    QoS2ReasonFailure,
     /// Topic alias is greater than max topic alias
     MaxTopicAlias,
     /// Unknown topic alias
@@ -70,6 +119,56 @@ pub enum ProtocolError {
     Io(io::Error),
 }
 
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProtocolError::PublishReadyError => f.write_str("publish service is not ready"),
+            ProtocolError::Decode(e) => write!(f, "decode error: {}", e),
+            ProtocolError::Encode(e) => write!(f, "encode error: {}", e),
+            ProtocolError::Unexpected(tp, name) => {
+                write!(f, "unexpected packet {:#x}, expected {}", tp, name)
+            }
+            ProtocolError::PacketIdRequired => {
+                f.write_str("packet with QoS > 0 must contain a non-zero packet id")
+            }
+            ProtocolError::DuplicatedPacketId => {
+                f.write_str("multiple in-flight publish packets share a packet id")
+            }
+            ProtocolError::PacketIdMismatch => {
+                f.write_str("ack packet id does not match the sent publish packet")
+            }
+            ProtocolError::UnexpectedPubRel => {
+                f.write_str("received PUBREL for a packet id that is not awaiting release")
+            }
+            ProtocolError::UnexpectedPubComp => {
+                f.write_str("received PUBCOMP for a packet id that is not awaiting completion")
+            }
+            ProtocolError::QoS2ReasonFailure => {
+                f.write_str("QoS 2 control packet carried a failure reason code")
+            }
+            ProtocolError::MaxTopicAlias => {
+                f.write_str("topic alias is greater than max topic alias")
+            }
+            ProtocolError::UnknownTopicAlias => f.write_str("unknown topic alias"),
+            ProtocolError::KeepAliveTimeout => f.write_str("keep alive timeout"),
+            ProtocolError::HandshakeTimeout => f.write_str("handshake timeout"),
+            ProtocolError::Disconnected => f.write_str("peer disconnected"),
+            ProtocolError::Io(e) => write!(f, "io error: {}", e),
+        }
+    }
+}
+
+impl error::Error for ProtocolError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ProtocolError::Decode(e) => Some(e),
+            ProtocolError::Encode(e) => Some(e),
+            ProtocolError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
 impl<E> From<Either<E, ProtocolError>> for MqttError<E> {
     fn from(err: Either<E, ProtocolError>) -> Self {
         match err {
@@ -175,6 +274,35 @@ pub enum DecodeError {
     Utf8Error(std::str::Utf8Error),
 }
 
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::InvalidProtocol => f.write_str("invalid protocol"),
+            DecodeError::InvalidLength => f.write_str("invalid length"),
+            DecodeError::MalformedPacket => f.write_str("malformed packet"),
+            DecodeError::UnsupportedProtocolLevel => f.write_str("unsupported protocol level"),
+            DecodeError::ConnectReservedFlagSet => f.write_str("connect reserved flag is set"),
+            DecodeError::ConnAckReservedFlagSet => f.write_str("connack reserved flag is set"),
+            DecodeError::InvalidClientId => f.write_str("invalid client id"),
+            DecodeError::UnsupportedPacketType => f.write_str("unsupported packet type"),
+            DecodeError::PacketIdRequired => {
+                f.write_str("packet with QoS > 0 must contain a non-zero packet id")
+            }
+            DecodeError::MaxSizeExceeded => f.write_str("maximum packet size exceeded"),
+            DecodeError::Utf8Error(e) => write!(f, "utf8 error: {}", e),
+        }
+    }
+}
+
+impl error::Error for DecodeError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            DecodeError::Utf8Error(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum EncodeError {
     InvalidLength,
@@ -183,6 +311,21 @@ pub enum EncodeError {
     UnsupportedVersion,
 }
 
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncodeError::InvalidLength => f.write_str("invalid length"),
+            EncodeError::MalformedPacket => f.write_str("malformed packet"),
+            EncodeError::PacketIdRequired => {
+                f.write_str("packet with QoS > 0 must contain a non-zero packet id")
+            }
+            EncodeError::UnsupportedVersion => f.write_str("unsupported protocol version"),
+        }
+    }
+}
+
+impl error::Error for EncodeError {}
+
 impl PartialEq for DecodeError {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
@@ -1,10 +1,13 @@
 use std::fmt;
 use std::time::Duration;
 
+use bytes::Bytes;
 use ntex::channel::mpsc;
+use ntex::util::{ByteString, SinkExt, StreamExt};
 use ntex_codec::Framed;
 
 use super::{codec, sink::MqttSink};
+use crate::error::ProtocolError;
 use crate::handshake::HandshakeResult;
 
 /// Connect message
@@ -45,17 +48,56 @@ impl<Io> Connect<Io> {
         &self.sink
     }
 
+    /// Authentication method requested by the client in the CONNECT packet.
+    pub fn authentication_method(&self) -> Option<&ByteString> {
+        self.connect.auth_method.as_ref()
+    }
+
+    /// Authentication data supplied by the client in the CONNECT packet.
+    pub fn authentication_data(&self) -> Option<&Bytes> {
+        self.connect.auth_data.as_ref()
+    }
+
+    /// Run one round of the v5 challenge-response authentication exchange.
+    ///
+    /// Sends an `AUTH` packet with reason code `ContinueAuthentication` and the
+    /// provided authentication data, then reads the client's `AUTH` reply off
+    /// the connection. Call repeatedly until the method returns an `AUTH` with
+    /// reason code `Success`, then finish the handshake with [`Connect::ack`].
+    pub async fn auth(&mut self, data: Bytes) -> Result<codec::Auth, ProtocolError> {
+        let method = self.connect.auth_method.clone();
+        let packet = codec::Auth {
+            reason_code: codec::AuthReason::ContinueAuthentication,
+            auth_method: method,
+            auth_data: Some(data),
+            ..Default::default()
+        };
+
+        let io = self.io.io();
+        io.send(codec::Packet::Auth(packet)).await.map_err(ProtocolError::Encode)?;
+
+        match io.next().await {
+            Some(Ok(codec::Packet::Auth(auth))) => Ok(auth),
+            Some(Ok(packet)) => Err(ProtocolError::Unexpected(packet.packet_type(), "AUTH")),
+            Some(Err(e)) => Err(ProtocolError::Decode(e)),
+            None => Err(ProtocolError::Disconnected),
+        }
+    }
+
     /// Ack connect message and set state
     pub fn ack<St>(self, st: St) -> ConnectAck<Io, St> {
         let mut packet = codec::ConnectAck::default();
         packet.reason_code = codec::ConnectAckReason::Success;
         packet.topic_alias_max = self.max_topic_alias;
 
+        self.sink.set_will(self.connect.last_will.clone());
+
         ConnectAck {
             io: self.io,
             sink: self.sink,
             inflight: self.inflight,
             session: Some(st),
+            will: self.connect.last_will,
             packet,
         }
     }
@@ -71,6 +113,7 @@ impl<Io> Connect<Io> {
             sink: self.sink,
             session: None,
             inflight: self.inflight,
+            will: self.connect.last_will,
             packet,
         }
     }
@@ -88,16 +131,28 @@ pub struct ConnectAck<Io, St> {
     pub(crate) session: Option<St>,
     pub(crate) inflight: usize,
     pub(crate) sink: MqttSink,
+    pub(crate) will: Option<codec::LastWill>,
     pub(crate) packet: codec::ConnectAck,
 }
 
 impl<Io, St> ConnectAck<Io, St> {
     /// Set idle keep-alive for the connection in seconds.
     ///
-    /// By default idle keep-alive is set to 30 seconds
-    pub fn keep_alive(mut self, timeout: u32) -> Self {
-        self.packet.session_expiry_interval_secs = Some(timeout);
-        self.io.set_keepalive_timeout(Duration::from_secs(timeout as u64));
+    /// This only adjusts the IO idle timeout (the ping interval); it does not
+    /// affect how long the broker retains session state. By default idle
+    /// keep-alive is set to 30 seconds.
+    pub fn keep_alive(mut self, secs: u32) -> Self {
+        self.io.set_keepalive_timeout(Duration::from_secs(secs as u64));
+        self
+    }
+
+    /// Set the session expiry interval in seconds.
+    ///
+    /// This controls `session_expiry_interval_secs` on the CONNACK packet, i.e.
+    /// how long the broker retains session state after the client disconnects.
+    /// It is independent from the [`keep_alive`](Self::keep_alive) ping interval.
+    pub fn session_expiry(mut self, secs: u32) -> Self {
+        self.packet.session_expiry_interval_secs = Some(secs);
         self
     }
 
@@ -115,4 +170,23 @@ impl<Io, St> ConnectAck<Io, St> {
         self.inflight = in_flight;
         self
     }
+
+    /// The client's Last Will, as parsed from the CONNECT packet.
+    ///
+    /// This is the message the server is expected to publish on abnormal
+    /// session teardown, after the will's `will_delay_interval_secs`.
+    pub fn will(&self) -> Option<&codec::LastWill> {
+        self.will.as_ref()
+    }
+
+    /// Observe or override the Last Will before the session starts.
+    ///
+    /// The closure receives the stored will (if any) and may rewrite its
+    /// payload, topic or `will_delay_interval_secs`, or clear it entirely to
+    /// suppress publication.
+    pub fn with_will(mut self, f: impl FnOnce(&mut Option<codec::LastWill>)) -> Self {
+        f(&mut self.will);
+        self.sink.set_will(self.will.clone());
+        self
+    }
 }
@@ -0,0 +1,365 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::num::NonZeroU16;
+use std::rc::Rc;
+use std::time::Duration;
+
+use bytes::Bytes;
+use ntex::channel::{mpsc, oneshot};
+use ntex::util::ByteString;
+
+use super::codec;
+use crate::error::ProtocolError;
+
+/// Mqtt client sink.
+///
+/// Holds the per-connection control-packet channel and the exactly-once
+/// (QoS 2) delivery state for both directions.
+#[derive(Clone)]
+pub struct MqttSink(Rc<MqttSinkInner>);
+
+struct MqttSinkInner {
+    tx: mpsc::Sender<codec::Packet>,
+    /// Client id of this connection, used as the will registry key.
+    client_id: ByteString,
+    /// Session-level will registry shared with the server.
+    wills: WillRegistry,
+    state: RefCell<SinkState>,
+}
+
+#[derive(Default)]
+struct SinkState {
+    next_id: u16,
+    /// Outbound QoS 2 publishes awaiting REC -> COMP, keyed by packet id.
+    outbound: HashMap<NonZeroU16, OutboundQoS2>,
+    /// Inbound QoS 2 publishes that received PUBREC and await PUBREL, held by
+    /// packet id so the payload can be delivered once the release arrives.
+    inbound: HashMap<NonZeroU16, codec::Publish>,
+    /// Configured Last Will for this session, armed on abnormal teardown.
+    will: Option<codec::LastWill>,
+}
+
+/// Server-side registry of pending Last Wills, keyed by client id.
+///
+/// This lives at the session-manager level, outside any single connection, so a
+/// delayed will survives teardown of the connection that armed it and can be
+/// cancelled if the same client reconnects within the `will_delay_interval`
+/// window. Publication is routed to the broker's publish ingress for fan-out to
+/// the will topic's subscribers, never back down the departed client's own
+/// control channel.
+#[derive(Clone)]
+pub struct WillRegistry(Rc<WillRegistryInner>);
+
+struct WillRegistryInner {
+    /// Broker publish ingress; armed wills are delivered here.
+    publish: mpsc::Sender<codec::Publish>,
+    /// Cancellation flags for armed wills, keyed by client id.
+    pending: RefCell<HashMap<ByteString, Rc<Cell<bool>>>>,
+}
+
+impl WillRegistry {
+    /// Create a registry that delivers fired wills to the given broker publish
+    /// ingress.
+    pub fn new(publish: mpsc::Sender<codec::Publish>) -> Self {
+        WillRegistry(Rc::new(WillRegistryInner {
+            publish,
+            pending: RefCell::new(HashMap::new()),
+        }))
+    }
+
+    /// Arm a will for publication after its `will_delay_interval_secs`.
+    ///
+    /// Called from the session-teardown hook when a connection ends abnormally.
+    /// A delay of zero publishes immediately. The publication is cancelled if
+    /// [`cancel`](Self::cancel) runs for the same client id before the delay
+    /// elapses, or if the client re-arms a newer will.
+    pub fn arm(&self, client_id: ByteString, will: codec::LastWill) {
+        let cancelled = Rc::new(Cell::new(false));
+        if let Some(prev) =
+            self.0.pending.borrow_mut().insert(client_id.clone(), cancelled.clone())
+        {
+            prev.set(true);
+        }
+
+        let delay = Duration::from_secs(will.will_delay_interval_secs.unwrap_or(0) as u64);
+        let registry = self.0.clone();
+        ntex::rt::spawn(async move {
+            if !delay.is_zero() {
+                ntex::time::sleep(delay).await;
+            }
+            if cancelled.get() {
+                return;
+            }
+            // Only fire if this exact arming is still the registered one.
+            let current = registry.pending.borrow().get(&client_id).cloned();
+            if current.map_or(false, |c| Rc::ptr_eq(&c, &cancelled)) {
+                registry.pending.borrow_mut().remove(&client_id);
+                let _ = registry.publish.send(codec::Publish::from(will));
+            }
+        });
+    }
+
+    /// Cancel a client's pending will (reconnect/resume or clean DISCONNECT).
+    pub fn cancel(&self, client_id: &ByteString) {
+        if let Some(cancelled) = self.0.pending.borrow_mut().remove(client_id) {
+            cancelled.set(true);
+        }
+    }
+}
+
+/// Outbound QoS 2 exchange stage.
+enum OutboundStage {
+    /// PUBLISH sent, waiting for PUBREC.
+    AwaitRec,
+    /// PUBREL sent, waiting for PUBCOMP.
+    AwaitComp,
+}
+
+struct OutboundQoS2 {
+    stage: OutboundStage,
+    completion: oneshot::Sender<Result<(), ProtocolError>>,
+}
+
+impl MqttSink {
+    pub(crate) fn new(
+        tx: mpsc::Sender<codec::Packet>,
+        client_id: ByteString,
+        wills: WillRegistry,
+    ) -> Self {
+        MqttSink(Rc::new(MqttSinkInner {
+            tx,
+            client_id,
+            wills,
+            state: RefCell::new(SinkState::default()),
+        }))
+    }
+
+    fn send(&self, pkt: codec::Packet) {
+        let _ = self.0.tx.send(pkt);
+    }
+
+    /// Install the Last Will for this session.
+    ///
+    /// Called from the handshake with the (possibly server-overridden) will so
+    /// that session teardown can honour it.
+    pub(crate) fn set_will(&self, will: Option<codec::LastWill>) {
+        self.0.state.borrow_mut().will = will;
+    }
+
+    /// Arm the Last Will on session teardown.
+    ///
+    /// This is the explicit teardown hook: the server calls it when the session
+    /// ends abnormally, handing the stored will to the session-level
+    /// [`WillRegistry`] so it is published after `will_delay_interval_secs` and
+    /// survives this connection being dropped.
+    pub(crate) fn arm_will(&self) {
+        if let Some(will) = self.0.state.borrow_mut().will.take() {
+            self.0.wills.arm(self.0.client_id.clone(), will);
+        }
+    }
+
+    /// Cancel a pending will publication (clean disconnect or reconnect).
+    ///
+    /// Clears both the configured will and any delayed publication already armed
+    /// in the registry for this client id.
+    pub(crate) fn cancel_will(&self) {
+        self.0.wills.cancel(&self.0.client_id);
+        self.0.state.borrow_mut().will = None;
+    }
+
+    /// Send a DISCONNECT packet and close the connection.
+    ///
+    /// Builds a v5 DISCONNECT with the given reason code, an optional reason
+    /// string and user properties, queues it on the control-packet channel,
+    /// then closes that channel so the dispatcher tears the connection down.
+    /// This lets a server tell a client *why* it is being dropped (e.g.
+    /// `ServerShuttingDown`, `SessionTakenOver`, `KeepAliveTimeout`).
+    ///
+    /// Delivery is best-effort: the sink owns only the control channel, not the
+    /// framed transport, so it relies on the dispatcher draining and flushing
+    /// the queued DISCONNECT before acting on the close. If the connection is
+    /// already gone the packet is silently dropped.
+    pub fn disconnect(
+        &self,
+        reason: codec::DisconnectReasonCode,
+        reason_string: Option<ByteString>,
+        user_properties: codec::UserProperties,
+    ) {
+        let packet = codec::Disconnect {
+            reason_code: reason,
+            reason_string,
+            user_properties,
+            ..Default::default()
+        };
+        // A normal server DISCONNECT ends the session cleanly, so the client's
+        // Last Will must not be published; any other reason is an abnormal
+        // teardown and leaves the scheduled will in place.
+        if matches!(reason, codec::DisconnectReasonCode::NormalDisconnection) {
+            self.cancel_will();
+        }
+        self.send(codec::Packet::Disconnect(packet));
+        self.0.tx.close();
+    }
+
+    /// Start a server-initiated re-authentication exchange mid-session.
+    ///
+    /// Sends an `AUTH` packet with reason code `ReAuthenticate` and the given
+    /// authentication method and data; the client's `AUTH` replies are driven
+    /// through the normal inbound control-packet path.
+    pub fn reauth(&self, method: ByteString, data: Bytes) {
+        let packet = codec::Auth {
+            reason_code: codec::AuthReason::ReAuthenticate,
+            auth_method: Some(method),
+            auth_data: Some(data),
+            ..Default::default()
+        };
+        self.send(codec::Packet::Auth(packet));
+    }
+
+    /// Begin an outbound QoS 2 publish.
+    ///
+    /// The returned receiver resolves once the full PUBLISH -> PUBREC ->
+    /// PUBREL -> PUBCOMP cycle completes; the packet id is retained for the
+    /// duration of the exchange and only released on completion or failure.
+    ///
+    /// This is the public entry point that starts the outbound exactly-once
+    /// cycle; [`publish_received`](Self::publish_received) and
+    /// [`publish_complete`](Self::publish_complete) drive it forward as the
+    /// matching PUBREC/PUBCOMP control packets arrive.
+    pub fn publish_qos2(
+        &self,
+        mut publish: codec::Publish,
+    ) -> oneshot::Receiver<Result<(), ProtocolError>> {
+        let (completion, rx) = oneshot::channel();
+        let mut state = self.0.state.borrow_mut();
+
+        let id = state.next_packet_id();
+        publish.packet_id = Some(id);
+        state.outbound.insert(id, OutboundQoS2 { stage: OutboundStage::AwaitRec, completion });
+        drop(state);
+
+        self.send(codec::Packet::Publish(publish));
+        rx
+    }
+
+    /// Handle an inbound PUBREC for an outbound QoS 2 publish.
+    pub fn publish_received(
+        &self,
+        ack: codec::PublishAck2,
+    ) -> Result<(), ProtocolError> {
+        let mut state = self.0.state.borrow_mut();
+        let awaiting_rec =
+            state.outbound.get(&ack.packet_id).map(|e| matches!(e.stage, OutboundStage::AwaitRec));
+        match awaiting_rec {
+            Some(true) if ack.reason_code.is_failure() => {
+                let entry = state.outbound.remove(&ack.packet_id).unwrap();
+                let _ = entry.completion.send(Err(ProtocolError::QoS2ReasonFailure));
+                Err(ProtocolError::QoS2ReasonFailure)
+            }
+            Some(true) => {
+                state.outbound.get_mut(&ack.packet_id).unwrap().stage = OutboundStage::AwaitComp;
+                drop(state);
+                self.send(codec::Packet::PublishRelease(codec::PublishAck2::new(ack.packet_id)));
+                Ok(())
+            }
+            _ => Err(ProtocolError::PacketIdMismatch),
+        }
+    }
+
+    /// Handle an inbound PUBCOMP, completing the outbound QoS 2 publish.
+    pub fn publish_complete(
+        &self,
+        ack: codec::PublishAck2,
+    ) -> Result<(), ProtocolError> {
+        let mut state = self.0.state.borrow_mut();
+        let awaiting_comp = state
+            .outbound
+            .get(&ack.packet_id)
+            .map(|e| matches!(e.stage, OutboundStage::AwaitComp));
+        match awaiting_comp {
+            Some(true) => {
+                let entry = state.outbound.remove(&ack.packet_id).unwrap();
+                if ack.reason_code.is_failure() {
+                    let _ = entry.completion.send(Err(ProtocolError::QoS2ReasonFailure));
+                    Err(ProtocolError::QoS2ReasonFailure)
+                } else {
+                    let _ = entry.completion.send(Ok(()));
+                    Ok(())
+                }
+            }
+            _ => Err(ProtocolError::UnexpectedPubComp),
+        }
+    }
+
+    /// Record an inbound QoS 2 PUBLISH and acknowledge it with PUBREC.
+    ///
+    /// The message itself is held until the matching PUBREL arrives; its packet
+    /// id is retained until then so it cannot be reused. Returns the recorded
+    /// packet id, or [`ProtocolError::PacketIdRequired`] if the PUBLISH carried
+    /// none.
+    pub fn receive_qos2(&self, publish: codec::Publish) -> Result<NonZeroU16, ProtocolError> {
+        let packet_id = publish.packet_id.ok_or(ProtocolError::PacketIdRequired)?;
+        self.0.state.borrow_mut().inbound.insert(packet_id, publish);
+        self.send(codec::Packet::PublishReceived(codec::PublishAck2::new(packet_id)));
+        Ok(packet_id)
+    }
+
+    /// Handle an inbound PUBREL, releasing the inbound packet id.
+    ///
+    /// Returns the held PUBLISH only when a matching PUBREC was previously
+    /// recorded, so the caller can deliver it to the publish service; PUBCOMP
+    /// has already been sent and the packet id released by the time it returns.
+    pub fn publish_release(&self, packet_id: NonZeroU16) -> Result<codec::Publish, ProtocolError> {
+        let held = self.0.state.borrow_mut().inbound.remove(&packet_id);
+        match held {
+            Some(publish) => {
+                self.send(codec::Packet::PublishComplete(codec::PublishAck2::new(packet_id)));
+                Ok(publish)
+            }
+            None => Err(ProtocolError::UnexpectedPubRel),
+        }
+    }
+
+    /// Feed an inbound QoS 2 control packet into the exactly-once state machine.
+    ///
+    /// This is the dispatcher integration point: the connection read loop routes
+    /// every QoS 2 `PUBLISH` and every `PUBREC`/`PUBREL`/`PUBCOMP` here to drive
+    /// the REC -> REL -> COMP cycle. Returns `Ok(Some(publish))` when an inbound
+    /// `PUBREL` releases a held message the caller must deliver to the publish
+    /// service, and `Ok(None)` for the ack-only transitions.
+    pub fn handle_qos2(
+        &self,
+        packet: codec::Packet,
+    ) -> Result<Option<codec::Publish>, ProtocolError> {
+        match packet {
+            codec::Packet::Publish(publish) if publish.qos == codec::QoS::ExactlyOnce => {
+                self.receive_qos2(publish)?;
+                Ok(None)
+            }
+            codec::Packet::PublishReceived(ack) => {
+                self.publish_received(ack)?;
+                Ok(None)
+            }
+            codec::Packet::PublishRelease(ack) => self.publish_release(ack.packet_id).map(Some),
+            codec::Packet::PublishComplete(ack) => {
+                self.publish_complete(ack)?;
+                Ok(None)
+            }
+            other => Err(ProtocolError::Unexpected(other.packet_type(), "QoS 2 control packet")),
+        }
+    }
+}
+
+impl SinkState {
+    /// Allocate the next free packet id, skipping ids still in flight.
+    fn next_packet_id(&mut self) -> NonZeroU16 {
+        loop {
+            self.next_id = self.next_id.wrapping_add(1);
+            if let Some(id) = NonZeroU16::new(self.next_id) {
+                if !self.outbound.contains_key(&id) {
+                    return id;
+                }
+            }
+        }
+    }
+}